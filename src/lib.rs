@@ -1,16 +1,31 @@
-use std::io::Result;
+use std::{
+  collections::HashMap,
+  ffi::c_void,
+  io::Result,
+  os::raw::c_int,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex, OnceLock,
+  },
+};
 
 use ffmpeg_next::{
   codec,
   color::Range,
-  encoder::{self, video::Video},
+  encoder::{self, audio::Audio as AudioEncoder, video::Video},
+  ffi,
   format::{
     context::{output, Output},
-    output, Flags, Pixel,
+    output, output_as, sample, Flags, Pixel, Sample,
   },
-  frame, Dictionary, Packet, Rational, Rescale,
+  frame,
+  software::{resampling, scaling},
+  ChannelLayout, Dictionary, Packet, Rational, Rescale,
+};
+use jni::{
+  objects::{GlobalRef, JObject, JString},
+  JNIEnv, JavaVM,
 };
-use jni::{objects::JString, JNIEnv};
 
 const OPTS: [(&str, &str); 3] = [
   ("preset", "ultrafast"),
@@ -18,6 +33,13 @@ const OPTS: [(&str, &str); 3] = [
   ("crf", "16"), // TODO - make this configurable (oh who cares dude honestly)
 ];
 
+const AUDIO_SAMPLE_FORMAT: Sample = Sample::F32(sample::Type::Packed);
+const AUDIO_BIT_RATE: usize = 160_000;
+
+const HLS_SEGMENT_PATTERN: &str = "seg_%05d.ts";
+
+const AVIO_BUFFER_SIZE: usize = 32 * 1024;
+
 struct JavaFrame {
   av_frame: frame::Video,
   original_yuv: (*mut u8, *mut u8, *mut u8),
@@ -26,19 +48,24 @@ struct JavaFrame {
 unsafe impl Sync for JavaFrame {}
 
 impl JavaFrame {
+  // `format` may be anything swscale understands (YUV444P straight from the
+  // game's mixer needs no conversion; packed formats like RGBA/BGRA from an
+  // OpenGL readback only use plane 0 and leave the other two pointers unused).
   fn new(
+    format: Pixel,
     width: u32,
     height: u32,
-    jvm_y_channel: *mut u8,
-    jvm_u_channel: *mut u8,
-    jvm_v_channel: *mut u8,
+    jvm_plane_0: *mut u8,
+    jvm_plane_1: *mut u8,
+    jvm_plane_2: *mut u8,
   ) -> JavaFrame {
     let mut av_frame = frame::Video::new(
-      Pixel::YUV444P,
+      format,
       width,
       height,
     );
     av_frame.set_color_range(Range::JPEG);
+    let plane_count = av_frame.planes();
 
     // Store the original yuv buffers for later cleanup
     let original_yuv = unsafe {
@@ -51,9 +78,13 @@ impl JavaFrame {
 
     // Change the underlying buffer that's in use by these frames
     unsafe {
-      (*av_frame.as_mut_ptr()).data[0] = jvm_y_channel;
-      (*av_frame.as_mut_ptr()).data[1] = jvm_u_channel;
-      (*av_frame.as_mut_ptr()).data[2] = jvm_v_channel;
+      (*av_frame.as_mut_ptr()).data[0] = jvm_plane_0;
+      if plane_count > 1 {
+        (*av_frame.as_mut_ptr()).data[1] = jvm_plane_1;
+      }
+      if plane_count > 2 {
+        (*av_frame.as_mut_ptr()).data[2] = jvm_plane_2;
+      }
     }
 
     JavaFrame {
@@ -63,6 +94,96 @@ impl JavaFrame {
   }
 }
 
+// Holds everything needed to push PCM from the game's mixer into the AAC
+// encoder. The mixer's format almost never matches what the encoder wants,
+// so a SwrContext sits in between to do the conversion.
+struct AudioTrack {
+  encoder: AudioEncoder,
+  resampler: resampling::Context,
+  stream_index: usize,
+  stream_time_base: Rational,
+  sample_index: u64,
+  channels: u16,
+  sample_rate: u32,
+}
+
+// Tracks the rolling window of .ts segments written in HLS mode so the
+// playlist can be rewritten from scratch every time a new one completes.
+struct Hls {
+  playlist_path: String,
+  seconds_per_segment: u32,
+  segment_index: u32,
+  segment_start_pts: i64,
+  current_segment: String,
+  completed: Vec<(String, f64)>,
+}
+
+// Converts a JavaFrame in whatever format/resolution the game captured
+// (e.g. RGBA from an OpenGL readback) into the YUV444P frame the encoder
+// actually wants, sized to the encoder's output dimensions.
+struct Scaler {
+  ctx: scaling::Context,
+  scaled: frame::Video,
+}
+
+// Lives behind the AVIOContext's opaque pointer so the write_packet
+// trampoline (a bare extern "C" fn, no `self`) can reach back into the JVM.
+struct StreamingCallback {
+  jvm: JavaVM,
+  consumer: GlobalRef,
+}
+
+// Owns the AVIOContext and its backing buffer that replace the usual
+// file-backed AVIO for `startEncodeStreaming`. Freed in Drop, mirroring
+// how JavaFrame::Drop restores its pointers before teardown.
+struct StreamingSink {
+  avio_ctx: *mut ffi::AVIOContext,
+  avio_buffer: *mut u8,
+  callback: *mut StreamingCallback,
+}
+
+unsafe impl Send for StreamingSink {}
+
+impl Drop for StreamingSink {
+  fn drop(&mut self) {
+    unsafe {
+      ffi::av_free(self.avio_buffer as *mut c_void);
+      ffi::avio_context_free(&mut self.avio_ctx);
+      drop(Box::from_raw(self.callback));
+    }
+  }
+}
+
+// AVIOContext write_packet callback: marshals the encoded bytes into a
+// direct ByteBuffer and hands them to the registered JVM consumer.
+unsafe extern "C" fn streaming_write_packet(
+  opaque: *mut c_void,
+  buf: *mut u8,
+  buf_size: c_int,
+) -> c_int {
+  let callback = &*(opaque as *const StreamingCallback);
+
+  let Ok(mut env) = callback.jvm.attach_current_thread() else {
+    return ffi::AVERROR_UNKNOWN;
+  };
+  let Ok(buffer) = (unsafe { env.new_direct_byte_buffer(buf, buf_size as usize) }) else {
+    return ffi::AVERROR_UNKNOWN;
+  };
+
+  let wrote = env.call_method(
+    &callback.consumer,
+    "write",
+    "(Ljava/nio/ByteBuffer;)V",
+    &[(&buffer).into()],
+  );
+
+  if wrote.is_err() {
+    return ffi::AVERROR_UNKNOWN;
+  }
+
+  buf_size
+}
+
 struct Renderer {
   frame_a: JavaFrame,
   frame_b: JavaFrame,
@@ -71,9 +192,34 @@ struct Renderer {
   encoder: Video,
   octx: Output,
   stream_time_base: Rational,
+  audio: Option<AudioTrack>,
+  hls: Option<Hls>,
+  streaming: Option<StreamingSink>,
+  scaler: Option<Scaler>,
+}
+
+// Holds raw pointers (via JavaFrame) that are only ever touched through the
+// registry's Mutex, so handing a Renderer across threads is sound.
+unsafe impl Send for Renderer {}
+
+impl Drop for Renderer {
+  fn drop(&mut self) {
+    // `streaming`'s pb is hand-built via avio_alloc_context, not opened
+    // through avio_open - but Output's own Drop unconditionally calls
+    // avio_close(pb), which is only valid for the latter and would free
+    // the buffer/context StreamingSink is about to free itself, a double
+    // free. Detach pb first so avio_close(NULL) is a no-op, leaving
+    // StreamingSink as sole owner.
+    if self.streaming.is_some() {
+      unsafe {
+        (*self.octx.as_mut_ptr()).pb = std::ptr::null_mut();
+      }
+    }
+  }
 }
 
 impl Renderer {
+  #[allow(clippy::too_many_arguments)]
   fn new(
     output_file: String,
     width: u32,
@@ -82,49 +228,537 @@ impl Renderer {
     frame_a: JavaFrame,
     frame_b: JavaFrame,
     is_proxy: bool,
+    with_audio: bool,
+    audio_channels: u16,
+    audio_sample_rate: u32,
+    is_hls: bool,
+    seconds_per_segment: u32,
+    input_format: Pixel,
+    input_width: u32,
+    input_height: u32,
+    requested_encoder: &str,
+  ) -> Result<Renderer> {
+    let first_segment = hls_segment_path(&output_file, 0);
+    let mut octx = if is_hls {
+      output_as(&first_segment, "mpegts")?
+    } else {
+      output(&output_file)?
+    };
+
+    let (encoder, global_header, encoder_format) = Self::open_video_stream(
+      &mut octx,
+      width,
+      height,
+      frame_rate,
+      is_proxy,
+      is_hls,
+      seconds_per_segment,
+      requested_encoder,
+    )?;
+
+    let audio = if with_audio {
+      Some(Self::open_audio_track(
+        &mut octx,
+        global_header,
+        audio_channels,
+        audio_sample_rate,
+      )?)
+    } else {
+      None
+    };
+
+    output::dump(&octx, 0, Some(&output_file));
+    octx.write_header()?;
+    let stream_time_base =
+      octx.stream(0).map_or(Rational(90000, 1), |s| s.time_base());
+
+    let hls = if is_hls {
+      Some(Hls {
+        playlist_path: output_file.clone(),
+        seconds_per_segment,
+        segment_index: 0,
+        segment_start_pts: 0,
+        current_segment: first_segment,
+        completed: Vec::new(),
+      })
+    } else {
+      None
+    };
+
+    let scaler =
+      Self::build_scaler(input_format, input_width, input_height, encoder_format, width, height)?;
+
+    Ok(Renderer {
+      frame_a,
+      frame_b,
+      frame_index: 0,
+      frame_rate,
+      encoder,
+      octx,
+      stream_time_base,
+      audio,
+      hls,
+      streaming: None,
+      scaler,
+    })
+  }
+
+  // Same recording, but the muxer writes through a custom AVIOContext
+  // instead of opening a file, so callers can stream to a socket, an
+  // in-memory buffer, or an upload pipeline.
+  #[allow(clippy::too_many_arguments)]
+  fn new_streaming(
+    jvm: JavaVM,
+    consumer: GlobalRef,
+    width: u32,
+    height: u32,
+    frame_rate: Rational,
+    frame_a: JavaFrame,
+    frame_b: JavaFrame,
+    is_proxy: bool,
+    with_audio: bool,
+    audio_channels: u16,
+    audio_sample_rate: u32,
+    input_format: Pixel,
+    input_width: u32,
+    input_height: u32,
+    requested_encoder: &str,
   ) -> Result<Renderer> {
-    let mut octx = output(&output_file)?;
+    let (mut octx, streaming) = Self::alloc_streaming_output(jvm, consumer)?;
+
+    let setup = Self::try_build_streaming_session(
+      &mut octx,
+      width,
+      height,
+      frame_rate,
+      is_proxy,
+      with_audio,
+      audio_channels,
+      audio_sample_rate,
+      input_format,
+      input_width,
+      input_height,
+      requested_encoder,
+    );
+
+    let (encoder, stream_time_base, audio, scaler) = match setup {
+      Ok(setup) => setup,
+      Err(err) => {
+        // `streaming` owns the hand-built pb; detach it from `octx` before
+        // Output's Drop runs (it unconditionally calls avio_close(pb),
+        // which would double-free what StreamingSink is about to free
+        // itself) - same reasoning as Renderer's Drop impl above.
+        unsafe {
+          (*octx.as_mut_ptr()).pb = std::ptr::null_mut();
+        }
+        return Err(err);
+      }
+    };
+
+    Ok(Renderer {
+      frame_a,
+      frame_b,
+      frame_index: 0,
+      frame_rate,
+      encoder,
+      octx,
+      stream_time_base,
+      audio,
+      hls: None,
+      streaming: Some(streaming),
+      scaler,
+    })
+  }
+
+  // The fallible part of new_streaming that opens the video/audio streams
+  // and writes the header against the already-allocated custom AVIOContext.
+  // Split out so new_streaming can detach `octx`'s pb on any error here
+  // before `octx`/`streaming` drop (see the comment at the call site).
+  #[allow(clippy::too_many_arguments)]
+  fn try_build_streaming_session(
+    octx: &mut Output,
+    width: u32,
+    height: u32,
+    frame_rate: Rational,
+    is_proxy: bool,
+    with_audio: bool,
+    audio_channels: u16,
+    audio_sample_rate: u32,
+    input_format: Pixel,
+    input_width: u32,
+    input_height: u32,
+    requested_encoder: &str,
+  ) -> Result<(Video, Rational, Option<AudioTrack>, Option<Scaler>)> {
+    let (encoder, global_header, encoder_format) = Self::open_video_stream(
+      octx,
+      width,
+      height,
+      frame_rate,
+      is_proxy,
+      false,
+      0,
+      requested_encoder,
+    )?;
+
+    let audio = if with_audio {
+      Some(Self::open_audio_track(
+        octx,
+        global_header,
+        audio_channels,
+        audio_sample_rate,
+      )?)
+    } else {
+      None
+    };
+
+    // This pb is non-seekable (no seek callback in alloc_streaming_output),
+    // but the plain MOV/MP4 muxer needs a seekable pb to patch moov/stco at
+    // write_trailer time. Fragmented mode writes moov/moof boxes as it goes
+    // instead, so header/trailer writes work over a pure byte stream.
+    octx.write_header_with(Dictionary::from_iter([(
+      "movflags",
+      "frag_keyframe+empty_moov+default_base_moof",
+    )]))?;
+    let stream_time_base =
+      octx.stream(0).map_or(Rational(90000, 1), |s| s.time_base());
+
+    let scaler =
+      Self::build_scaler(input_format, input_width, input_height, encoder_format, width, height)?;
+
+    Ok((encoder, stream_time_base, audio, scaler))
+  }
+
+  // Builds the YUV444P conversion path when the capture's format or size
+  // doesn't already match what the encoder wants; returns None (no-op) when
+  // they line up, so the common case pays nothing extra.
+  fn build_scaler(
+    input_format: Pixel,
+    input_width: u32,
+    input_height: u32,
+    encoder_format: Pixel,
+    output_width: u32,
+    output_height: u32,
+  ) -> Result<Option<Scaler>> {
+    if input_format == encoder_format
+      && input_width == output_width
+      && input_height == output_height
+    {
+      return Ok(None);
+    }
+
+    let ctx = scaling::Context::get(
+      input_format,
+      input_width,
+      input_height,
+      encoder_format,
+      output_width,
+      output_height,
+      scaling::Flags::BILINEAR,
+    )?;
+    let scaled = frame::Video::new(encoder_format, output_width, output_height);
+
+    Ok(Some(Scaler { ctx, scaled }))
+  }
+
+  // Builds an AVFormatContext backed by an AVIOContext whose write_packet
+  // callback marshals bytes into a registered JVM ByteBuffer consumer.
+  fn alloc_streaming_output(jvm: JavaVM, consumer: GlobalRef) -> Result<(Output, StreamingSink)> {
+    unsafe {
+      let mut raw_ctx: *mut ffi::AVFormatContext = std::ptr::null_mut();
+      let format_name = std::ffi::CString::new("mp4").unwrap();
+      let ret = ffi::avformat_alloc_output_context2(
+        &mut raw_ctx,
+        std::ptr::null_mut(),
+        format_name.as_ptr(),
+        std::ptr::null(),
+      );
+      if ret < 0 || raw_ctx.is_null() {
+        return Err(std::io::Error::from_raw_os_error(ret));
+      }
+
+      let avio_buffer = ffi::av_malloc(AVIO_BUFFER_SIZE) as *mut u8;
+      let callback = Box::into_raw(Box::new(StreamingCallback { jvm, consumer }));
+
+      let avio_ctx = ffi::avio_alloc_context(
+        avio_buffer,
+        AVIO_BUFFER_SIZE as c_int,
+        1, // writable
+        callback as *mut c_void,
+        None,
+        Some(streaming_write_packet),
+        None,
+      );
+      (*raw_ctx).pb = avio_ctx;
+      (*raw_ctx).flags |= ffi::AVFMT_FLAG_CUSTOM_IO as i32;
+
+      let octx = Output::wrap(raw_ctx);
+      let streaming = StreamingSink {
+        avio_ctx,
+        avio_buffer,
+        callback,
+      };
+
+      Ok((octx, streaming))
+    }
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  fn open_video_stream(
+    octx: &mut Output,
+    width: u32,
+    height: u32,
+    frame_rate: Rational,
+    is_proxy: bool,
+    is_hls: bool,
+    seconds_per_segment: u32,
+    requested_encoder: &str,
+  ) -> Result<(Video, bool, Pixel)> {
     let global_header = octx.format().flags().contains(Flags::GLOBAL_HEADER);
-    let mut ost = octx.add_stream(encoder::find_by_name("libx264"))?;
+
+    let (mut codec, mut pixel_format, mut video_opts, hw_device_type) =
+      Self::resolve_video_codec(requested_encoder, is_proxy, is_hls, frame_rate, seconds_per_segment);
+
+    // Stand up the hw device context once (vaapi/qsv need one attached
+    // before the encoder can even open) and reuse the same handle for both
+    // the probe below and the real encoder, instead of creating one twice.
+    let mut hw_device_ctx = None;
+    let mut hw_encoder_usable = true;
+    if let Some(device_type) = hw_device_type {
+      match Self::create_hw_device(device_type) {
+        Some(device_ctx) => hw_device_ctx = Some(device_ctx),
+        None => hw_encoder_usable = false,
+      }
+    }
+
+    if hw_encoder_usable
+      && requested_encoder != "libx264"
+      && !requested_encoder.is_empty()
+      && !Self::probe_video_encoder(
+        codec,
+        pixel_format,
+        width,
+        height,
+        frame_rate,
+        global_header,
+        &video_opts,
+        hw_device_ctx,
+      )
+    {
+      hw_encoder_usable = false;
+    }
+
+    if !hw_encoder_usable {
+      if let Some(device_ctx) = hw_device_ctx.take() {
+        Self::free_hw_device(device_ctx);
+      }
+      // The requested hardware encoder isn't available on this machine -
+      // fall back to libx264 rather than aborting the recording.
+      (codec, pixel_format, video_opts, _) =
+        Self::resolve_video_codec("libx264", is_proxy, is_hls, frame_rate, seconds_per_segment);
+    }
+
+    let mut ost = octx.add_stream(codec)?;
     let mut encoder = ost.codec().encoder().video()?;
     encoder.set_width(width);
     encoder.set_height(height);
-    encoder.set_format(Pixel::YUV444P);
+    encoder.set_format(pixel_format);
     encoder.set_color_range(Range::JPEG);
     encoder.set_frame_rate(Some(frame_rate));
     encoder.set_time_base(frame_rate.invert());
     if global_header {
       encoder.set_flags(codec::Flags::GLOBAL_HEADER);
     }
+    if let Some(device_ctx) = hw_device_ctx {
+      Self::attach_hw_device(&mut encoder, device_ctx);
+      Self::free_hw_device(device_ctx);
+    }
 
-    encoder.open_with(Dictionary::from_iter(if is_proxy {
-      [
-        ("preset", "ultrafast"),
-        ("profile", "high444"),
-        ("crf", "28"),
-      ]
-    } else {
-      OPTS
-    }))?;
+    encoder.open_with(Dictionary::from_iter(video_opts))?;
 
     encoder = ost.codec().encoder().video()?;
     ost.set_parameters(encoder);
 
     let encoder = ost.codec().encoder().video()?;
 
-    output::dump(&octx, 0, Some(&output_file));
-    octx.write_header()?;
-    let stream_time_base =
-      octx.stream(0).map_or(Rational(90000, 1), |s| s.time_base());
+    Ok((encoder, global_header, pixel_format))
+  }
 
-    Ok(Renderer {
-      frame_a,
-      frame_b,
-      frame_index: 0,
-      frame_rate,
+  // Picks the ffmpeg codec, pixel format, option dictionary, and (for
+  // encoders that need one) hw device type for a requested encoder name,
+  // falling back to libx264 for anything unrecognized (including an empty
+  // string, the "just use the default" case).
+  fn resolve_video_codec(
+    requested_encoder: &str,
+    is_proxy: bool,
+    is_hls: bool,
+    frame_rate: Rational,
+    seconds_per_segment: u32,
+  ) -> (codec::Codec, Pixel, Vec<(String, String)>, Option<ffi::AVHWDeviceType>) {
+    let keyint = is_hls.then(|| (frame_rate.numerator() as u32 * seconds_per_segment).to_string());
+
+    match requested_encoder {
+      "h264_nvenc" | "hevc_nvenc" => {
+        let codec = encoder::find_by_name(requested_encoder)
+          .unwrap_or_else(|| encoder::find_by_name("libx264").expect("libx264 is always available"));
+        let mut opts = vec![
+          ("preset".to_string(), "p4".to_string()),
+          ("rc".to_string(), "vbr".to_string()),
+          ("cq".to_string(), "19".to_string()),
+          // Plain "high" rejects 4:4:4 input on nvenc; it needs the 4:4:4
+          // profile explicitly, same as libx264's "high444" below.
+          ("profile".to_string(), "high444p".to_string()),
+        ];
+        if let Some(keyint) = &keyint {
+          opts.push(("g".to_string(), keyint.clone()));
+        }
+        (codec, Pixel::YUV444P, opts, None)
+      }
+      "h264_qsv" => {
+        let codec = encoder::find_by_name(requested_encoder)
+          .unwrap_or_else(|| encoder::find_by_name("libx264").expect("libx264 is always available"));
+        let mut opts = vec![("preset".to_string(), "veryfast".to_string())];
+        if let Some(keyint) = &keyint {
+          opts.push(("g".to_string(), keyint.clone()));
+        }
+        (codec, Pixel::NV12, opts, Some(ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_QSV))
+      }
+      "h264_vaapi" => {
+        let codec = encoder::find_by_name(requested_encoder)
+          .unwrap_or_else(|| encoder::find_by_name("libx264").expect("libx264 is always available"));
+        let opts = keyint.map_or_else(Vec::new, |keyint| vec![("g".to_string(), keyint)]);
+        (codec, Pixel::NV12, opts, Some(ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI))
+      }
+      _ => {
+        let codec = encoder::find_by_name("libx264").expect("libx264 is always available");
+        let mut opts: Vec<(String, String)> = if is_proxy {
+          [
+            ("preset", "ultrafast"),
+            ("profile", "high444"),
+            ("crf", "28"),
+          ]
+          .iter()
+          .map(|(k, v)| (k.to_string(), v.to_string()))
+          .collect()
+        } else {
+          OPTS.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        if let Some(keyint) = keyint {
+          opts.push(("g".to_string(), keyint.clone()));
+          opts.push(("keyint_min".to_string(), keyint));
+        }
+        (codec, Pixel::YUV444P, opts, None)
+      }
+    }
+  }
+
+  // Creates an AVHWDeviceContext of the given type (vaapi/qsv need one
+  // attached before the encoder can even open, not just to encode).
+  // Returns None if this machine has no such device (e.g. no render node).
+  // The caller owns the returned buffer ref and must eventually pass it to
+  // `free_hw_device`.
+  fn create_hw_device(device_type: ffi::AVHWDeviceType) -> Option<*mut ffi::AVBufferRef> {
+    unsafe {
+      let mut hw_device_ctx: *mut ffi::AVBufferRef = std::ptr::null_mut();
+      let ret = ffi::av_hwdevice_ctx_create(
+        &mut hw_device_ctx,
+        device_type,
+        std::ptr::null(),
+        std::ptr::null_mut(),
+        0,
+      );
+      (ret >= 0).then_some(hw_device_ctx)
+    }
+  }
+
+  // Attaches a new reference to an already-created hw device context onto a
+  // not-yet-opened encoder context. The caller keeps owning `hw_device_ctx`
+  // and is still responsible for freeing it.
+  fn attach_hw_device(encoder: &mut Video, hw_device_ctx: *mut ffi::AVBufferRef) {
+    unsafe {
+      (*encoder.as_mut_ptr()).hw_device_ctx = ffi::av_buffer_ref(hw_device_ctx);
+    }
+  }
+
+  fn free_hw_device(hw_device_ctx: *mut ffi::AVBufferRef) {
+    let mut hw_device_ctx = hw_device_ctx;
+    unsafe {
+      ffi::av_buffer_unref(&mut hw_device_ctx);
+    }
+  }
+
+  // Opens a throwaway encoder context (not attached to any stream) to check
+  // whether a hardware encoder can actually be initialized on this machine,
+  // without touching the real output.
+  fn probe_video_encoder(
+    codec: codec::Codec,
+    pixel_format: Pixel,
+    width: u32,
+    height: u32,
+    frame_rate: Rational,
+    global_header: bool,
+    options: &[(String, String)],
+    hw_device_ctx: Option<*mut ffi::AVBufferRef>,
+  ) -> bool {
+    let Ok(context) = codec::context::Context::new_with_codec(codec).encoder().video() else {
+      return false;
+    };
+    let mut encoder = context;
+    encoder.set_width(width);
+    encoder.set_height(height);
+    encoder.set_format(pixel_format);
+    encoder.set_frame_rate(Some(frame_rate));
+    encoder.set_time_base(frame_rate.invert());
+    if global_header {
+      encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+    }
+    if let Some(device_ctx) = hw_device_ctx {
+      Self::attach_hw_device(&mut encoder, device_ctx);
+    }
+
+    encoder
+      .open_with(Dictionary::from_iter(options.to_vec()))
+      .is_ok()
+  }
+
+  fn open_audio_track(
+    octx: &mut Output,
+    global_header: bool,
+    channels: u16,
+    sample_rate: u32,
+  ) -> Result<AudioTrack> {
+    let mut ost = octx.add_stream(encoder::find(codec::Id::AAC))?;
+    let mut encoder = ost.codec().encoder().audio()?;
+    let channel_layout = ChannelLayout::default(channels as i32);
+    encoder.set_rate(sample_rate as i32);
+    encoder.set_channel_layout(channel_layout);
+    encoder.set_channels(channels as i32);
+    encoder.set_format(Sample::F32(sample::Type::Packed));
+    encoder.set_bit_rate(AUDIO_BIT_RATE);
+    if global_header {
+      encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+    }
+
+    encoder.open_as(encoder::find(codec::Id::AAC))?;
+    ost.set_parameters(&encoder);
+    let encoder = ost.codec().encoder().audio()?;
+
+    let resampler = resampling::Context::get(
+      AUDIO_SAMPLE_FORMAT,
+      channel_layout,
+      sample_rate,
+      encoder.format(),
+      encoder.channel_layout(),
+      encoder.rate(),
+    )?;
+
+    Ok(AudioTrack {
       encoder,
-      octx,
-      stream_time_base,
+      resampler,
+      stream_index: ost.index(),
+      stream_time_base: ost.time_base(),
+      sample_index: 0,
+      channels,
+      sample_rate,
     })
   }
 
@@ -138,21 +772,39 @@ impl Renderer {
       &mut self.frame_a
     };
 
-    // println!("oh I see buffer b? {}", use_buffer_b);
-    frame.av_frame.set_pts(Some(pts));
+    // If the capture isn't already YUV444P at the encoder's size, swscale it
+    // into the scratch frame the scaler owns; otherwise encode it as-is.
+    if let Some(scaler) = self.scaler.as_mut() {
+      if scaler.ctx.run(&frame.av_frame, &mut scaler.scaled).is_err() {
+        return false;
+      }
+      scaler.scaled.set_pts(Some(pts));
+    } else {
+      // println!("oh I see buffer b? {}", use_buffer_b);
+      frame.av_frame.set_pts(Some(pts));
+    }
+
+    let encode_frame = match self.scaler.as_ref() {
+      Some(scaler) => &scaler.scaled,
+      None => &frame.av_frame,
+    };
 
     // println!("About to send_frame {}", self.frame_index);
-    if self.encoder.send_frame(&frame.av_frame).is_err() {
+    if self.encoder.send_frame(encode_frame).is_err() {
       return false;
     }
 
     // println!("Sent frame, receiving packet {}", self.frame_index);
     let mut encoded = Packet::empty();
+    let mut last_key_pts = None;
     while self.encoder.receive_packet(&mut encoded).is_ok() {
       //   println!("Received packet, writing {}", self.frame_index);
       encoded.set_stream(0);
       //   println!("actually writing {}", self.frame_index);
       // TODO - ^^^ do we need this when we're like doing audio and stuff?
+      if encoded.is_key() {
+        last_key_pts = encoded.pts();
+      }
 
       if encoded.write_interleaved(&mut self.octx).is_err() {
         return false;
@@ -162,6 +814,110 @@ impl Renderer {
 
     self.frame_index += 1;
 
+    if let Some(key_pts) = last_key_pts {
+      self.maybe_roll_hls_segment(key_pts);
+    }
+
+    true
+  }
+
+  // Checks whether we've crossed a segment boundary on this keyframe and, if
+  // so, closes the current .ts segment and opens the next one.
+  fn maybe_roll_hls_segment(&mut self, key_pts: i64) {
+    let Some(hls) = self.hls.as_ref() else {
+      return;
+    };
+
+    let elapsed = (key_pts - hls.segment_start_pts) as f64
+      * self.stream_time_base.numerator() as f64
+      / self.stream_time_base.denominator() as f64;
+    if elapsed < hls.seconds_per_segment as f64 {
+      return;
+    }
+
+    let _ = self.roll_hls_segment(key_pts);
+  }
+
+  fn roll_hls_segment(&mut self, cut_pts: i64) -> Result<()> {
+    self.octx.write_trailer()?;
+
+    let hls = self.hls.as_mut().expect("roll_hls_segment called without hls state");
+    let duration = (cut_pts - hls.segment_start_pts) as f64
+      * self.stream_time_base.numerator() as f64
+      / self.stream_time_base.denominator() as f64;
+    hls.completed.push((hls.current_segment.clone(), duration));
+    hls.segment_index += 1;
+    hls.segment_start_pts = cut_pts;
+    hls.current_segment = hls_segment_path(&hls.playlist_path, hls.segment_index);
+
+    let mut octx = output_as(&hls.current_segment, "mpegts")?;
+    let mut ost = octx.add_stream(self.encoder.codec())?;
+    ost.set_parameters(&self.encoder);
+
+    if let Some(audio) = self.audio.as_mut() {
+      let mut audio_ost = octx.add_stream(audio.encoder.codec())?;
+      audio_ost.set_parameters(&audio.encoder);
+      audio.stream_index = audio_ost.index();
+    }
+
+    octx.write_header()?;
+    self.octx = octx;
+
+    self.write_hls_playlist()
+  }
+
+  fn write_hls_playlist(&self) -> Result<()> {
+    let Some(hls) = self.hls.as_ref() else {
+      return Ok(());
+    };
+
+    let playlist = format_hls_playlist(hls.seconds_per_segment, hls.segment_index, &hls.completed);
+
+    std::fs::write(&hls.playlist_path, playlist)
+  }
+
+  // Resamples PCM from the game's mixer into the encoder's format/layout
+  // and pushes it through to the muxer, same shape as send_frame above.
+  fn send_audio(&mut self, samples: *const f32, nb_samples: u32) -> bool {
+    let Some(audio) = self.audio.as_mut() else {
+      return true;
+    };
+
+    let mut input = frame::Audio::new(
+      AUDIO_SAMPLE_FORMAT,
+      nb_samples as usize,
+      ChannelLayout::default(audio.channels as i32),
+    );
+    unsafe {
+      std::ptr::copy_nonoverlapping(
+        samples,
+        (*input.as_mut_ptr()).data[0] as *mut f32,
+        nb_samples as usize * audio.channels as usize,
+      );
+    }
+
+    let mut resampled = frame::Audio::empty();
+    if audio.resampler.run(&input, &mut resampled).is_err() {
+      return false;
+    }
+
+    let pts = (audio.sample_index as i64)
+      .rescale(Rational(1, audio.sample_rate as i32), audio.stream_time_base);
+    resampled.set_pts(Some(pts));
+    audio.sample_index += resampled.samples() as u64;
+
+    if audio.encoder.send_frame(&resampled).is_err() {
+      return false;
+    }
+
+    let mut encoded = Packet::empty();
+    while audio.encoder.receive_packet(&mut encoded).is_ok() {
+      encoded.set_stream(audio.stream_index);
+      if encoded.write_interleaved(&mut self.octx).is_err() {
+        return false;
+      }
+    }
+
     true
   }
 
@@ -171,13 +927,129 @@ impl Renderer {
     while self.encoder.receive_packet(&mut encoded).is_ok() {
       encoded.write_interleaved(&mut self.octx)?;
     }
+
+    if let Some(audio) = self.audio.as_mut() {
+      audio.encoder.send_eof()?;
+      let mut encoded = Packet::empty();
+      while audio.encoder.receive_packet(&mut encoded).is_ok() {
+        encoded.set_stream(audio.stream_index);
+        encoded.write_interleaved(&mut self.octx)?;
+      }
+    }
+
     self.octx.write_trailer()?;
 
+    if let Some(hls) = self.hls.as_mut() {
+      let last_pts = (self.frame_index as i64).rescale(self.frame_rate.invert(), self.stream_time_base);
+      let duration = (last_pts - hls.segment_start_pts) as f64
+        * self.stream_time_base.numerator() as f64
+        / self.stream_time_base.denominator() as f64;
+      hls.completed.push((hls.current_segment.clone(), duration));
+      self.write_hls_playlist()?;
+    }
+
     Ok(())
   }
 }
 
-static mut RENDERER_STATE: Option<Renderer> = None;
+// Derives a segment file's path from the playlist path, e.g.
+// "stream.m3u8" + 3 -> "stream_seg_00003.ts".
+fn hls_segment_path(playlist_path: &str, index: u32) -> String {
+  let stem = playlist_path.trim_end_matches(".m3u8");
+  let name = HLS_SEGMENT_PATTERN.replacen("%05d", &format!("{:05}", index), 1);
+  format!("{}_{}", stem, name)
+}
+
+// Builds the m3u8 text for the rolling window of completed segments.
+// `segment_index` is the next segment that hasn't completed yet, so the
+// media sequence of the oldest entry still in `completed` is
+// `segment_index - completed.len()`.
+fn format_hls_playlist(
+  seconds_per_segment: u32,
+  segment_index: u32,
+  completed: &[(String, f64)],
+) -> String {
+  let mut playlist = String::new();
+  playlist.push_str("#EXTM3U\n");
+  playlist.push_str("#EXT-X-VERSION:3\n");
+  playlist.push_str(&format!("#EXT-X-TARGETDURATION:{}\n", seconds_per_segment));
+  let first_sequence = segment_index.saturating_sub(completed.len() as u32);
+  playlist.push_str(&format!("#EXT-X-MEDIA-SEQUENCE:{}\n", first_sequence));
+  for (segment, duration) in completed {
+    playlist.push_str(&format!("#EXTINF:{:.3},\n{}\n", duration, segment));
+  }
+  playlist
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn hls_segment_path_numbers_and_strips_m3u8_suffix() {
+    assert_eq!(
+      hls_segment_path("stream.m3u8", 0),
+      "stream_seg_00000.ts"
+    );
+    assert_eq!(
+      hls_segment_path("stream.m3u8", 3),
+      "stream_seg_00003.ts"
+    );
+    assert_eq!(
+      hls_segment_path("stream.m3u8", 12345),
+      "stream_seg_12345.ts"
+    );
+  }
+
+  #[test]
+  fn hls_segment_path_leaves_non_m3u8_paths_alone() {
+    assert_eq!(hls_segment_path("stream.mp4", 1), "stream.mp4_seg_00001.ts");
+  }
+
+  #[test]
+  fn format_hls_playlist_with_no_completed_segments() {
+    let playlist = format_hls_playlist(4, 0, &[]);
+    assert_eq!(
+      playlist,
+      "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:0\n"
+    );
+  }
+
+  #[test]
+  fn format_hls_playlist_media_sequence_tracks_the_rolling_window() {
+    let completed = vec![
+      ("stream_seg_00001.ts".to_string(), 4.0),
+      ("stream_seg_00002.ts".to_string(), 4.016),
+    ];
+    let playlist = format_hls_playlist(4, 3, &completed);
+
+    assert!(playlist.contains("#EXT-X-MEDIA-SEQUENCE:1\n"));
+    assert!(playlist.contains("#EXTINF:4.000,\nstream_seg_00001.ts\n"));
+    assert!(playlist.contains("#EXTINF:4.016,\nstream_seg_00002.ts\n"));
+  }
+}
+
+// Maps the small integer code the Kotlin side sends for the capture's pixel
+// format onto the ffmpeg Pixel it corresponds to.
+fn pixel_from_code(code: u32) -> Pixel {
+  match code {
+    1 => Pixel::RGBA,
+    2 => Pixel::BGRA,
+    _ => Pixel::YUV444P,
+  }
+}
+
+// Keyed by the session id handed back from startEncode so several
+// recordings (main output, proxy, extra camera angles, ...) can run at
+// once instead of fighting over one global slot. Each session gets its own
+// Mutex so a slow encode/write on one session's sendFrame doesn't block
+// another session's JNI calls - only the table lookup itself is shared.
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+fn renderer_registry() -> &'static Mutex<HashMap<u64, Arc<Mutex<Renderer>>>> {
+  static REGISTRY: OnceLock<Mutex<HashMap<u64, Arc<Mutex<Renderer>>>>> = OnceLock::new();
+  REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+}
 
 #[no_mangle]
 extern "C" fn Java_me_aris_recordingmod_RendererKt_startEncode(
@@ -194,21 +1066,115 @@ extern "C" fn Java_me_aris_recordingmod_RendererKt_startEncode(
   u_b: *mut u8,
   v_b: *mut u8,
   is_proxy: bool,
-) -> bool {
-  let frame_a = JavaFrame::new(width, height, y_a, u_a, v_a);
-  let frame_b = JavaFrame::new(width, height, y_b, u_b, v_b);
-  unsafe {
-    RENDERER_STATE = Renderer::new(
-      env.get_string(file).unwrap().into(),
-      width,
-      height,
-      Rational(fps, 1),
-      frame_a,
-      frame_b,
-      is_proxy,
-    )
-    .ok();
-    RENDERER_STATE.is_some()
+  with_audio: bool,
+  audio_channels: u16,
+  audio_sample_rate: u32,
+  is_hls: bool,
+  seconds_per_segment: u32,
+  input_pixel_format: u32,
+  input_width: u32,
+  input_height: u32,
+  encoder_name: JString,
+) -> i64 {
+  let input_format = pixel_from_code(input_pixel_format);
+  let frame_a = JavaFrame::new(input_format, input_width, input_height, y_a, u_a, v_a);
+  let frame_b = JavaFrame::new(input_format, input_width, input_height, y_b, u_b, v_b);
+  let requested_encoder: String = env.get_string(encoder_name).unwrap().into();
+  let renderer = Renderer::new(
+    env.get_string(file).unwrap().into(),
+    width,
+    height,
+    Rational(fps, 1),
+    frame_a,
+    frame_b,
+    is_proxy,
+    with_audio,
+    audio_channels,
+    audio_sample_rate,
+    is_hls,
+    seconds_per_segment,
+    input_format,
+    input_width,
+    input_height,
+    &requested_encoder,
+  );
+
+  match renderer {
+    Ok(renderer) => {
+      let session_id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+      renderer_registry()
+        .lock()
+        .unwrap()
+        .insert(session_id, Arc::new(Mutex::new(renderer)));
+      session_id as i64
+    }
+    Err(_) => -1,
+  }
+}
+
+#[no_mangle]
+extern "C" fn Java_me_aris_recordingmod_RendererKt_startEncodeStreaming(
+  env: JNIEnv,
+  _: *const (),
+  consumer: JObject,
+  width: u32,
+  height: u32,
+  fps: i32,
+  y_a: *mut u8,
+  u_a: *mut u8,
+  v_a: *mut u8,
+  y_b: *mut u8,
+  u_b: *mut u8,
+  v_b: *mut u8,
+  is_proxy: bool,
+  with_audio: bool,
+  audio_channels: u16,
+  audio_sample_rate: u32,
+  input_pixel_format: u32,
+  input_width: u32,
+  input_height: u32,
+  encoder_name: JString,
+) -> i64 {
+  let input_format = pixel_from_code(input_pixel_format);
+  let frame_a = JavaFrame::new(input_format, input_width, input_height, y_a, u_a, v_a);
+  let frame_b = JavaFrame::new(input_format, input_width, input_height, y_b, u_b, v_b);
+  let requested_encoder: String = env.get_string(encoder_name).unwrap().into();
+
+  let Ok(jvm) = env.get_java_vm() else {
+    return -1;
+  };
+  let Ok(consumer) = env.new_global_ref(consumer) else {
+    return -1;
+  };
+
+  let renderer = Renderer::new_streaming(
+    jvm,
+    consumer,
+    width,
+    height,
+    Rational(fps, 1),
+    frame_a,
+    frame_b,
+    is_proxy,
+    with_audio,
+    audio_channels,
+    audio_sample_rate,
+    input_format,
+    input_width,
+    input_height,
+    &requested_encoder,
+  );
+
+  match renderer {
+    Ok(renderer) => {
+      let session_id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+      renderer_registry()
+        .lock()
+        .unwrap()
+        .insert(session_id, Arc::new(Mutex::new(renderer)));
+      session_id as i64
+    }
+    Err(_) => -1,
   }
 }
 
@@ -216,14 +1182,40 @@ extern "C" fn Java_me_aris_recordingmod_RendererKt_startEncode(
 extern "C" fn Java_me_aris_recordingmod_RendererKt_sendFrame(
   _: *const (),
   _: *const (),
+  session_id: i64,
   use_bufer_b: bool,
 ) -> bool {
-  let renderer = unsafe { &mut RENDERER_STATE };
+  let renderer = renderer_registry()
+    .lock()
+    .unwrap()
+    .get(&(session_id as u64))
+    .cloned();
 
-  if let Some(renderer) = renderer {
-    renderer.send_frame(use_bufer_b)
-  } else {
-    true
+  match renderer {
+    Some(renderer) => renderer.lock().unwrap().send_frame(use_bufer_b),
+    None => true,
+  }
+}
+
+#[no_mangle]
+extern "C" fn Java_me_aris_recordingmod_RendererKt_sendAudio(
+  _: *const (),
+  _: *const (),
+  session_id: i64,
+  samples_ptr: *const f32,
+  nb_samples: u32,
+  _channels: u32,
+  _sample_rate: u32,
+) -> bool {
+  let renderer = renderer_registry()
+    .lock()
+    .unwrap()
+    .get(&(session_id as u64))
+    .cloned();
+
+  match renderer {
+    Some(renderer) => renderer.lock().unwrap().send_audio(samples_ptr, nb_samples),
+    None => true,
   }
 }
 
@@ -231,12 +1223,12 @@ extern "C" fn Java_me_aris_recordingmod_RendererKt_sendFrame(
 extern "C" fn Java_me_aris_recordingmod_RendererKt_finishEncode(
   _: *const (),
   _: *const (),
+  session_id: i64,
 ) {
-  let renderer = unsafe { &mut RENDERER_STATE };
+  let renderer = renderer_registry().lock().unwrap().remove(&(session_id as u64));
   if let Some(renderer) = renderer {
-    let _ = renderer.finish_render();
+    let _ = renderer.lock().unwrap().finish_render();
   }
-  unsafe { RENDERER_STATE = None }
 }
 
 impl Drop for JavaFrame {